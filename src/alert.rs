@@ -0,0 +1,205 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+
+use crate::json_portal::Sek;
+use crate::Offer;
+
+const ALERT_CONFIG_FILE: &str = "alerts.toml";
+
+/// How big a price drop has to be before it's worth telling anyone about.
+#[derive(Debug, Clone, Copy)]
+pub enum Threshold {
+    Sek(i32),
+    Percent(f64),
+}
+
+impl Threshold {
+    fn is_met(&self, old_cost: i32, new_cost: i32) -> bool {
+        if new_cost >= old_cost {
+            return false;
+        }
+        let drop = old_cost - new_cost;
+        match self {
+            Threshold::Sek(min_drop) => drop >= *min_drop,
+            Threshold::Percent(min_pct) => {
+                old_cost > 0 && (drop as f64 / old_cost as f64) * 100.0 >= *min_pct
+            }
+        }
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.strip_suffix('%') {
+            Some(pct) => Ok(Threshold::Percent(
+                pct.parse().context("Invalid percent threshold")?,
+            )),
+            None => Ok(Threshold::Sek(s.parse().context("Invalid SEK threshold")?)),
+        }
+    }
+}
+
+/// SMTP credentials and addressing, read from `alerts.toml`.
+#[derive(Debug, Deserialize)]
+pub struct AlertConfig {
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    pub to: String,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+impl AlertConfig {
+    pub fn load() -> Result<Self> {
+        Self::load_from(ALERT_CONFIG_FILE)
+    }
+
+    fn load_from(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+/// A single product whose price fell between two runs of `update()`.
+pub struct PriceDrop {
+    pub offer: Offer,
+    pub old_cost_1st_year: Sek,
+    pub new_cost_1st_year: Sek,
+    pub old_cost_2nd_year: Sek,
+    pub new_cost_2nd_year: Sek,
+}
+
+/// Compare a freshly scraped offer against its previously stored snapshot and
+/// report a drop if either the list price or the 1st-year cost fell by at
+/// least `threshold`.
+pub fn detect_drop(
+    offer: &Offer,
+    previous: &crate::history::HistoryRow,
+    threshold: Threshold,
+) -> Option<PriceDrop> {
+    let old_offer = Offer {
+        isp: offer.isp.clone(),
+        location: offer.location.clone(),
+        product_id: offer.product_id,
+        product_name: offer.product_name.clone(),
+        heading: offer.heading.clone(),
+        campaign_descr: previous.campaign_descr.clone(),
+        list_price: previous.list_price.into(),
+        discounted_price: previous.discounted_price.into(),
+        discount_duration: previous.discount_duration,
+        start_cost: previous.start_cost.into(),
+        speed_up: previous.speed_up,
+        speed_down: previous.speed_down,
+        bind_time: offer.bind_time,
+        leave_time: offer.leave_time,
+    };
+
+    let old_cost_1st_year = old_offer.calc_cost_1st_year();
+    let new_cost_1st_year = offer.calc_cost_1st_year();
+    let list_price_drop_met = threshold.is_met(previous.list_price, offer.list_price.i32());
+    let cost_1st_year_drop_met =
+        threshold.is_met(old_cost_1st_year.i32(), new_cost_1st_year.i32());
+
+    if !list_price_drop_met && !cost_1st_year_drop_met {
+        return None;
+    }
+
+    Some(PriceDrop {
+        offer: offer.clone(),
+        old_cost_1st_year,
+        new_cost_1st_year,
+        old_cost_2nd_year: old_offer.calc_cost_2nd_year(),
+        new_cost_2nd_year: offer.calc_cost_2nd_year(),
+    })
+}
+
+fn format_drop(drop: &PriceDrop) -> String {
+    format!(
+        "{} {} ({}): year 1 {} -> {}, year 2 {} -> {}",
+        drop.offer.isp,
+        drop.offer.product_name,
+        drop.offer.speed_str(),
+        drop.old_cost_1st_year,
+        drop.new_cost_1st_year,
+        drop.old_cost_2nd_year,
+        drop.new_cost_2nd_year,
+    )
+}
+
+pub fn notify_desktop(drops: &[PriceDrop]) -> Result<()> {
+    for drop in drops {
+        notify_rust::Notification::new()
+            .summary(&format!("Price drop: {}", drop.offer.isp))
+            .body(&format_drop(drop))
+            .show()
+            .context("Failed to show desktop notification")?;
+    }
+    Ok(())
+}
+
+pub fn send_email(config: &AlertConfig, drops: &[PriceDrop]) -> Result<()> {
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{Message, SmtpTransport, Transport};
+
+    let mut body = String::from("Fiber prices just dropped:\n\n");
+    for drop in drops {
+        body.push_str(&format_drop(drop));
+        body.push('\n');
+    }
+
+    let email = Message::builder()
+        .from(config.from.parse().context("Invalid from address")?)
+        .to(config.to.parse().context("Invalid to address")?)
+        .subject("Fiber price drop detected")
+        .body(body)
+        .context("Failed to build alert email")?;
+
+    let creds = Credentials::new(config.username.clone(), config.password.clone());
+    let mailer = SmtpTransport::relay(&config.smtp_host)
+        .context("Failed to set up SMTP transport")?
+        .port(config.smtp_port)
+        .credentials(creds)
+        .build();
+
+    mailer.send(&email).context("Failed to send alert email")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Threshold;
+
+    #[test]
+    fn parses_sek_and_percent() {
+        assert!(matches!("100".parse::<Threshold>().unwrap(), Threshold::Sek(100)));
+        assert!(matches!("5%".parse::<Threshold>().unwrap(), Threshold::Percent(p) if p == 5.0));
+        assert!("not a number".parse::<Threshold>().is_err());
+    }
+
+    #[test]
+    fn is_met_respects_sek_threshold() {
+        let threshold = Threshold::Sek(100);
+        assert!(!threshold.is_met(1000, 999), "a 1 kr drop should not meet a 100 kr threshold");
+        assert!(threshold.is_met(1000, 900));
+        assert!(!threshold.is_met(900, 1000), "a price increase is never a met threshold");
+    }
+
+    #[test]
+    fn is_met_respects_percent_threshold() {
+        let threshold = Threshold::Percent(10.0);
+        assert!(!threshold.is_met(1000, 950), "a 5% drop should not meet a 10% threshold");
+        assert!(threshold.is_met(1000, 900));
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+
+const CONFIG_FILE: &str = "openuniverse.toml";
+
+/// The accessId Kungsbacka's address used before locations became configurable.
+const DEFAULT_ACCESS_ID: u32 = 1137975;
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    #[serde(rename = "location")]
+    pub locations: Vec<Location>,
+}
+
+/// One address to fetch offers for, as configured in `openuniverse.toml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Location {
+    pub name: String,
+    pub access_id: u32,
+    #[serde(default)]
+    pub is_company: bool,
+}
+
+impl Config {
+    /// Load `openuniverse.toml`, or fall back to the single address this tool
+    /// used to be hardcoded to if no config file exists.
+    pub fn load() -> Result<Self> {
+        match std::fs::read_to_string(CONFIG_FILE) {
+            Ok(text) => {
+                toml::from_str(&text).with_context(|| format!("Failed to parse {}", CONFIG_FILE))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", CONFIG_FILE)),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            locations: vec![Location {
+                name: "default".to_owned(),
+                access_id: DEFAULT_ACCESS_ID,
+                is_company: false,
+            }],
+        }
+    }
+}
+
+impl Location {
+    pub fn internet_url(&self) -> String {
+        format!(
+            "https://selfservice.ip-only.se/api/consumer-selfservice-backend/v1/public/service-offers?accessId={}&isCompany={}&onlyOrderableOffers=false&priorityOption=ALL_OFFERS",
+            self.access_id, self.is_company
+        )
+    }
+
+    pub fn internet_file(&self) -> String {
+        format!("internet_{}.json", self.name)
+    }
+
+    /// URL of a product's detail page for this location's access id.
+    pub fn product_page_url(&self, product_id: u32) -> String {
+        format!(
+            "https://portal.openuniverse.se/best%C3%A4ll/tj%C3%A4nster/{}/produkt-detaljer/{}",
+            self.access_id, product_id
+        )
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use spreadsheet_ods::{CellStyle, WorkBook};
+
+use crate::Offer;
+
+const CSV_HEADER: [&str; 12] = [
+    "Location",
+    "ISP",
+    "Product name",
+    "Speed down",
+    "Speed up",
+    "List price",
+    "Discounted price",
+    "Discount duration",
+    "Start cost",
+    "Year 1 cost",
+    "Year 2 cost",
+    "Campaign",
+];
+
+/// Write `offers` as a CSV file, one row per offer.
+pub fn write_csv(offers: &[Offer], path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let mut writer =
+        csv::Writer::from_path(path).with_context(|| format!("Failed to create {}", path.display()))?;
+
+    writer.write_record(CSV_HEADER)?;
+    for offer in offers {
+        writer.write_record(&[
+            offer.location.clone(),
+            offer.isp.clone(),
+            offer.product_name.clone(),
+            offer.speed_down.to_string(),
+            offer.speed_up.to_string(),
+            offer.list_price.i32().to_string(),
+            offer.discounted_price.i32().to_string(),
+            offer.discount_duration.to_string(),
+            offer.start_cost.i32().to_string(),
+            offer.calc_cost_1st_year().i32().to_string(),
+            offer.calc_cost_2nd_year().i32().to_string(),
+            offer.campaign_descr.clone(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write `offers` as an OpenDocument spreadsheet, one row per offer.
+pub fn write_ods(offers: &[Offer], path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let mut workbook = WorkBook::new_empty();
+    let header_style = workbook.add_cellstyle(CellStyle::new("header", &Default::default()));
+    let mut sheet = spreadsheet_ods::Sheet::new("Offers");
+
+    for (col, title) in CSV_HEADER.iter().enumerate() {
+        sheet.set_styled_value(0, col as u32, *title, &header_style);
+    }
+
+    for (row, offer) in offers.iter().enumerate() {
+        let row = row as u32 + 1;
+        sheet.set_value(row, 0, offer.location.as_str());
+        sheet.set_value(row, 1, offer.isp.as_str());
+        sheet.set_value(row, 2, offer.product_name.as_str());
+        sheet.set_value(row, 3, offer.speed_down as f64);
+        sheet.set_value(row, 4, offer.speed_up as f64);
+        sheet.set_value(row, 5, offer.list_price.i32() as f64);
+        sheet.set_value(row, 6, offer.discounted_price.i32() as f64);
+        sheet.set_value(row, 7, offer.discount_duration as f64);
+        sheet.set_value(row, 8, offer.start_cost.i32() as f64);
+        sheet.set_value(row, 9, offer.calc_cost_1st_year().i32() as f64);
+        sheet.set_value(row, 10, offer.calc_cost_2nd_year().i32() as f64);
+        sheet.set_value(row, 11, offer.campaign_descr.as_str());
+    }
+
+    workbook.push_sheet(sheet);
+    spreadsheet_ods::write_ods(&mut workbook, path)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
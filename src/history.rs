@@ -0,0 +1,231 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::Offer;
+
+const HISTORY_DB_FILE: &str = "offers_history.sqlite3";
+
+/// One stored snapshot of a product's pricing at a point in time.
+#[derive(Debug)]
+pub struct HistoryRow {
+    pub fetched_at: i64,
+    pub list_price: i32,
+    pub discounted_price: i32,
+    pub discount_duration: i32,
+    pub start_cost: i32,
+    pub speed_down: u16,
+    pub speed_up: u16,
+    pub campaign_descr: String,
+}
+
+pub struct PriceHistory {
+    conn: Connection,
+}
+
+impl PriceHistory {
+    pub fn open() -> Result<Self> {
+        let conn = Connection::open(HISTORY_DB_FILE)
+            .with_context(|| format!("Failed to open {}", HISTORY_DB_FILE))?;
+        Self::from_connection(conn)
+    }
+
+    #[cfg(test)]
+    fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS price_history (
+                product_id        INTEGER NOT NULL,
+                location          TEXT NOT NULL,
+                fetched_at        INTEGER NOT NULL,
+                list_price        INTEGER NOT NULL,
+                discounted_price  INTEGER NOT NULL,
+                discount_duration INTEGER NOT NULL,
+                start_cost        INTEGER NOT NULL,
+                speed_down        INTEGER NOT NULL,
+                speed_up          INTEGER NOT NULL,
+                campaign_descr    TEXT NOT NULL,
+                PRIMARY KEY (product_id, location, fetched_at)
+            )",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Insert a row for each offer whose pricing changed since the last stored
+    /// snapshot for that product at that location. Offers that are unchanged
+    /// are skipped so the table only ever grows when something actually
+    /// happened.
+    pub fn record_offers(&mut self, fetched_at: i64, offers: &[Offer]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for offer in offers {
+            let last = Self::latest_row(&tx, offer.product_id, &offer.location)?;
+            let changed = match &last {
+                None => true,
+                Some(last) => {
+                    last.list_price != offer.list_price.i32()
+                        || last.discounted_price != offer.discounted_price.i32()
+                        || last.discount_duration != offer.discount_duration
+                        || last.start_cost != offer.start_cost.i32()
+                        || last.speed_down != offer.speed_down
+                        || last.speed_up != offer.speed_up
+                        || last.campaign_descr != offer.campaign_descr
+                }
+            };
+
+            if changed {
+                tx.execute(
+                    "INSERT INTO price_history
+                        (product_id, location, fetched_at, list_price, discounted_price,
+                         discount_duration, start_cost, speed_down, speed_up, campaign_descr)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        offer.product_id,
+                        offer.location,
+                        fetched_at,
+                        offer.list_price.i32(),
+                        offer.discounted_price.i32(),
+                        offer.discount_duration,
+                        offer.start_cost.i32(),
+                        offer.speed_down as i32,
+                        offer.speed_up as i32,
+                        offer.campaign_descr,
+                    ],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Fetch the stored history rows for a single product at a single
+    /// location, oldest first.
+    pub fn rows_for(&self, product_id: u32, location: &str) -> Result<Vec<HistoryRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT fetched_at, list_price, discounted_price, discount_duration,
+                    start_cost, speed_down, speed_up, campaign_descr
+             FROM price_history
+             WHERE product_id = ?1 AND location = ?2
+             ORDER BY fetched_at ASC",
+        )?;
+        let rows = stmt
+            .query_map(params![product_id, location], |row| {
+                Ok(HistoryRow {
+                    fetched_at: row.get(0)?,
+                    list_price: row.get(1)?,
+                    discounted_price: row.get(2)?,
+                    discount_duration: row.get(3)?,
+                    start_cost: row.get(4)?,
+                    speed_down: row.get::<_, i32>(5)? as u16,
+                    speed_up: row.get::<_, i32>(6)? as u16,
+                    campaign_descr: row.get(7)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// All distinct (product id, location) pairs that have at least one
+    /// stored row.
+    pub fn known_product_ids(&self) -> Result<Vec<(u32, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT product_id, location FROM price_history
+             ORDER BY product_id ASC, location ASC",
+        )?;
+        let ids = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)? as u32, row.get::<_, String>(1)?))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(ids)
+    }
+
+    /// The most recently stored snapshot for a product at a location, if any.
+    pub fn latest_for(&self, product_id: u32, location: &str) -> Result<Option<HistoryRow>> {
+        Self::latest_row(&self.conn, product_id, location)
+    }
+
+    fn latest_row(conn: &Connection, product_id: u32, location: &str) -> Result<Option<HistoryRow>> {
+        let mut stmt = conn.prepare(
+            "SELECT fetched_at, list_price, discounted_price, discount_duration,
+                    start_cost, speed_down, speed_up, campaign_descr
+             FROM price_history
+             WHERE product_id = ?1 AND location = ?2
+             ORDER BY fetched_at DESC
+             LIMIT 1",
+        )?;
+        stmt.query_row(params![product_id, location], |row| {
+            Ok(HistoryRow {
+                fetched_at: row.get(0)?,
+                list_price: row.get(1)?,
+                discounted_price: row.get(2)?,
+                discount_duration: row.get(3)?,
+                start_cost: row.get(4)?,
+                speed_down: row.get::<_, i32>(5)? as u16,
+                speed_up: row.get::<_, i32>(6)? as u16,
+                campaign_descr: row.get(7)?,
+            })
+        })
+        .optional()
+        .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PriceHistory;
+    use crate::Offer;
+
+    fn offer(product_id: u32, location: &str, list_price: i32) -> Offer {
+        serde_json::from_value(serde_json::json!({
+            "isp": "Test ISP",
+            "location": location,
+            "product_id": product_id,
+            "product_name": "Test product",
+            "heading": "Test product",
+            "campaign_descr": "",
+            "list_price": list_price,
+            "discounted_price": list_price,
+            "discount_duration": 0,
+            "start_cost": 0,
+            "speed_up": 100,
+            "speed_down": 100,
+            "bind_time": 0,
+            "leave_time": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn record_offers_skips_unchanged_rows() -> anyhow::Result<()> {
+        let mut history = PriceHistory::open_in_memory()?;
+
+        history.record_offers(1, &[offer(1, "home", 100)])?;
+        history.record_offers(2, &[offer(1, "home", 100)])?;
+        history.record_offers(3, &[offer(1, "home", 90)])?;
+
+        let rows = history.rows_for(1, "home")?;
+        assert_eq!(rows.len(), 2, "unchanged snapshot at t=2 should not be stored");
+        assert_eq!(rows[0].fetched_at, 1);
+        assert_eq!(rows[1].fetched_at, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn record_offers_keeps_locations_independent() -> anyhow::Result<()> {
+        let mut history = PriceHistory::open_in_memory()?;
+
+        history.record_offers(1, &[offer(1, "home", 100), offer(1, "office", 200)])?;
+
+        let home = history.latest_for(1, "home")?.unwrap();
+        let office = history.latest_for(1, "office")?.unwrap();
+        assert_eq!(home.list_price, 100);
+        assert_eq!(office.list_price, 200);
+
+        let ids = history.known_product_ids()?;
+        assert_eq!(ids, vec![(1, "home".to_owned()), (1, "office".to_owned())]);
+        Ok(())
+    }
+}
@@ -27,7 +27,7 @@ impl ProductOffers {
             .with_context(|| format!("Failed to parse json file {}", filename.display()))
     }
 
-    pub fn get_internet_offers(&self) -> Vec<Offer> {
+    pub fn get_internet_offers(&self, location: &str) -> Vec<Offer> {
         let price_descr: Regex =
             Regex::new(r"(\d+) kr i (\d+) månader, därefter ordinarie pris (\d+) kr").unwrap();
         let mut offers = vec![];
@@ -53,6 +53,7 @@ impl ProductOffers {
             };
             offers.push(Offer {
                 isp: p.company_name.clone(),
+                location: location.to_owned(),
                 product_id: p.id.0 as _,
                 product_name: p.product_name.clone(),
                 heading: p.product_name.clone(),
@@ -5,22 +5,31 @@ extern crate serde_derive;
 #[macro_use]
 extern crate tabular;
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use rand::Rng;
+use rayon::prelude::*;
 
+use crate::config::{Config, Location};
+use crate::history::PriceHistory;
 use crate::json_portal::{ProductOffers, Sek};
 
+mod alert;
+mod config;
+mod export;
+mod history;
 mod json_portal;
+mod parse;
 
-const INTERNET_URL: &str = "https://selfservice.ip-only.se/api/consumer-selfservice-backend/v1/public/service-offers?accessId=1137975&isCompany=false&onlyOrderableOffers=false&priorityOption=ALL_OFFERS";
-const INTERNET_FILE: &str = "internet.json";
-const PRODUCT_PAGE_URL: &str =
-    "https://portal.openuniverse.se/best%C3%A4ll/tj%C3%A4nster/1137975/produkt-detaljer/<prod_id>";
-const PRODUCT_PAGE_FILE: &str = "product_<prod_id>.html";
+const DEFAULT_ENRICH_CONCURRENCY: usize = 4;
 
 type Months = i32;
 type SEK = Sek;
@@ -29,6 +38,8 @@ type MBit = u16;
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Offer {
     isp: String,
+    #[serde(default)]
+    location: String,
     product_id: u32,
     product_name: String,
     heading: String,
@@ -70,35 +81,140 @@ macro_rules! sort_offers {
     };
 }
 
-fn download() -> Result<()> {
-    println!("Downloading offer listings");
-
-    let response = attohttpc::get(INTERNET_URL).send().context(INTERNET_URL)?;
-    let internet_file = File::create(INTERNET_FILE).context(INTERNET_FILE)?;
-    response.write_to(internet_file)?;
+fn download(config: &Config) -> Result<()> {
+    for location in &config.locations {
+        println!("Downloading offer listings for {}", location.name);
+        let url = location.internet_url();
+        let response = attohttpc::get(&url).send().context(url)?;
+        let internet_file = location.internet_file();
+        let file = File::create(&internet_file).context(internet_file)?;
+        response.write_to(file)?;
+    }
 
     Ok(())
 }
 
-fn fetch_details_page(product_id: u32) -> Result<PathBuf> {
-    let pid_str = product_id.to_string();
-    let url = PRODUCT_PAGE_URL.replace("<prod_id>", &pid_str);
-    let filename: PathBuf = PRODUCT_PAGE_FILE.replace("<prod_id>", &pid_str).into();
+fn product_page_file(location: &Location, product_id: u32) -> PathBuf {
+    format!("product_{}_{}.html", location.name, product_id).into()
+}
+
+fn fetch_details_page(product_id: u32, location: &Location) -> Result<PathBuf> {
+    let url = location.product_page_url(product_id);
+    let filename = product_page_file(location, product_id);
     let response = attohttpc::get(url).send()?;
     let file = File::create(&filename)?;
     response.write_to(file)?;
     Ok(filename)
 }
 
-fn update(no_download: bool) -> Result<()> {
+/// Load a cached product detail page, downloading and caching it first unless
+/// `no_download` is set. Returns `None` when `no_download` is set and nothing
+/// is cached yet.
+fn load_or_fetch_details_page(
+    product_id: u32,
+    location: &Location,
+    no_download: bool,
+) -> Result<Option<String>> {
+    let filename = product_page_file(location, product_id);
+    if no_download {
+        return if filename.exists() {
+            Ok(Some(load_file(&filename)?))
+        } else {
+            Ok(None)
+        };
+    }
+    let filename = fetch_details_page(product_id, location).context(product_id)?;
+    Ok(Some(load_file(&filename)?))
+}
+
+/// Fetch each offer's product page, in parallel on a bounded worker pool, and
+/// merge in the richer campaign description it contains. This is what makes
+/// `parse::parse_campaign_descr` actually get used. Each offer's product page
+/// is fetched under its own location's access id, since the same product id
+/// can mean a different campaign at a different address.
+fn enrich_offers(
+    offers: &mut [Offer],
+    locations: &[Location],
+    no_download: bool,
+    concurrency: usize,
+) -> Result<()> {
+    let locations_by_name: HashMap<&str, &Location> =
+        locations.iter().map(|l| (l.name.as_str(), l)).collect();
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(concurrency.max(1))
+        .build()
+        .context("Failed to build enrichment thread pool")?;
+
+    let descriptions: Vec<Option<String>> = pool.install(|| {
+        offers
+            .par_iter()
+            .map(|offer| {
+                let product_id = offer.product_id;
+                let location = match locations_by_name.get(offer.location.as_str()) {
+                    Some(location) => *location,
+                    None => {
+                        eprintln!(
+                            "No configured location named {:?}, skipping enrichment for product {product_id}",
+                            offer.location
+                        );
+                        return None;
+                    }
+                };
+                let html = match load_or_fetch_details_page(product_id, location, no_download) {
+                    Ok(html) => html,
+                    Err(err) => {
+                        eprintln!("Failed to fetch product page for {product_id}: {err:#}");
+                        return None;
+                    }
+                }?;
+                match parse::parse_campaign_descr(&html) {
+                    Ok(descr) => descr,
+                    Err(err) => {
+                        eprintln!("Failed to parse product page for {product_id}: {err}");
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+
+    for (offer, descr) in offers.iter_mut().zip(descriptions) {
+        if let Some(descr) = descr {
+            offer.campaign_descr = descr;
+        }
+    }
+    Ok(())
+}
+
+fn update(no_download: bool, alerts: AlertOpts, concurrency: usize) -> Result<()> {
+    let fetched_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("System clock is before the Unix epoch")?
+        .as_secs() as i64;
+
+    let config = Config::load()?;
     if !no_download {
-        download().context("Failed to download offers")?;
+        download(&config).context("Failed to download offers")?;
+    }
+
+    let mut internet_offers = Vec::new();
+    for location in &config.locations {
+        let products = ProductOffers::from_file(location.internet_file())?;
+        internet_offers.extend(products.get_internet_offers(&location.name));
     }
-    // let mut internet_offers = parse_internet_overview_page(load_file(INTERNET_FILE)?.as_ref());
-    let products = ProductOffers::from_file(INTERNET_FILE)?;
+    internet_offers.sort_by(|a, b| a.location.cmp(&b.location).then(a.product_id.cmp(&b.product_id)));
+
+    println!("Enriching offers from product pages");
+    enrich_offers(&mut internet_offers, &config.locations, no_download, concurrency)
+        .context("Failed to enrich offers from product pages")?;
 
-    let mut internet_offers = products.get_internet_offers();
-    internet_offers.sort_by_key(|offer| offer.product_id);
+    let mut history = PriceHistory::open().context("Failed to open price history database")?;
+    let drops = alerts.detect_drops(&history, &internet_offers)?;
+    history
+        .record_offers(fetched_at, &internet_offers)
+        .context("Failed to record price history")?;
+    alerts.deliver(&drops)?;
 
     let x = serde_json::to_vec_pretty(&internet_offers)?;
     println!("Saving JSON data");
@@ -108,17 +224,194 @@ fn update(no_download: bool) -> Result<()> {
     Ok(())
 }
 
-fn dump() -> Result<()> {
+/// Which alert channels to use and the drop size that should trigger them.
+#[derive(Clone)]
+struct AlertOpts {
+    desktop: bool,
+    email: bool,
+    threshold: alert::Threshold,
+}
+
+impl AlertOpts {
+    fn detect_drops(
+        &self,
+        history: &PriceHistory,
+        offers: &[Offer],
+    ) -> Result<Vec<alert::PriceDrop>> {
+        if !self.desktop && !self.email {
+            return Ok(Vec::new());
+        }
+        let mut drops = Vec::new();
+        for offer in offers {
+            if let Some(previous) = history.latest_for(offer.product_id, &offer.location)? {
+                if let Some(drop) = alert::detect_drop(offer, &previous, self.threshold) {
+                    drops.push(drop);
+                }
+            }
+        }
+        Ok(drops)
+    }
+
+    fn deliver(&self, drops: &[alert::PriceDrop]) -> Result<()> {
+        if drops.is_empty() {
+            return Ok(());
+        }
+        println!("{} price drop(s) detected", drops.len());
+        if self.desktop {
+            alert::notify_desktop(drops).context("Failed to send desktop notification")?;
+        }
+        if self.email {
+            let config = alert::AlertConfig::load().context("Failed to load alerts.toml")?;
+            alert::send_email(&config, drops).context("Failed to send alert email")?;
+        }
+        Ok(())
+    }
+}
+
+const WATCH_MAX_RETRIES: u32 = 5;
+
+/// Loop `update()` forever, sleeping `interval` between runs, until the user
+/// hits Ctrl-C. A failing `download()` is retried with exponential backoff
+/// and jitter rather than aborting the whole loop.
+fn watch(interval: humantime::Duration, alerts: AlertOpts, concurrency: usize) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || {
+            println!("Stopping after the current cycle...");
+            running.store(false, Ordering::SeqCst);
+        })
+        .context("Failed to install Ctrl-C handler")?;
+    }
+
+    while running.load(Ordering::SeqCst) {
+        run_update_with_backoff(&alerts, concurrency, &running);
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+        sleep_interruptibly(interval.into(), &running);
+    }
+    println!("Watch stopped.");
+    Ok(())
+}
+
+fn run_update_with_backoff(alerts: &AlertOpts, concurrency: usize, running: &AtomicBool) {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match update(false, alerts.clone(), concurrency) {
+            Ok(()) => return,
+            Err(err) if attempt >= WATCH_MAX_RETRIES || !running.load(Ordering::SeqCst) => {
+                eprintln!("Update failed after {attempt} attempt(s), giving up: {err:#}");
+                return;
+            }
+            Err(err) => {
+                let backoff = Duration::from_secs(2u64.pow(attempt.min(6)));
+                let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..500));
+                eprintln!(
+                    "Update attempt {attempt} failed: {err:#}. Retrying in {:.1}s.",
+                    (backoff + jitter).as_secs_f64()
+                );
+                sleep_interruptibly(backoff + jitter, running);
+            }
+        }
+    }
+}
+
+/// Sleep in small steps so a Ctrl-C during a long sleep is noticed promptly.
+fn sleep_interruptibly(duration: Duration, running: &AtomicBool) {
+    let step = Duration::from_millis(200);
+    let mut remaining = duration;
+    while !remaining.is_zero() && running.load(Ordering::SeqCst) {
+        let nap = step.min(remaining);
+        std::thread::sleep(nap);
+        remaining -= nap;
+    }
+}
+
+fn history(product_id: Option<u32>, location: Option<String>) -> Result<()> {
+    let history = PriceHistory::open().context("Failed to open price history database")?;
+
+    let mut products = history.known_product_ids()?;
+    if let Some(id) = product_id {
+        products.retain(|(pid, _)| *pid == id);
+    }
+    if let Some(location) = &location {
+        products.retain(|(_, loc)| loc == location);
+    }
+
+    for (product_id, location) in products {
+        let rows = history.rows_for(product_id, &location)?;
+        if rows.is_empty() {
+            println!("No history for product {} ({})", product_id, location);
+            continue;
+        }
+
+        println!("Product {} ({})", product_id, location);
+        let mut table = tabular::Table::new("{:<} {:>} {:>} {:>}");
+        table.add_row(row!("Date", "List price", "Discounted", "Δ vs previous"));
+
+        let mut previous_discounted: Option<i32> = None;
+        for row in &rows {
+            let date = format_unix_timestamp(row.fetched_at);
+            let delta = match previous_discounted {
+                Some(prev) => format!("{:+}", row.discounted_price - prev),
+                None => "-".to_owned(),
+            };
+            table.add_row(row! {
+                date,
+                Sek::from(row.list_price),
+                Sek::from(row.discounted_price),
+                delta,
+            });
+            previous_discounted = Some(row.discounted_price);
+        }
+        println!("{}", table);
+    }
+
+    Ok(())
+}
+
+fn format_unix_timestamp(secs: i64) -> String {
+    let datetime = chrono::NaiveDateTime::from_timestamp_opt(secs, 0)
+        .expect("Stored timestamp should always be a valid Unix time");
+    datetime.format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn dump(format: DumpFormat, output: Option<PathBuf>, location: Option<String>) -> Result<()> {
     let mut offers = load_offers_from_json()?;
+    if let Some(location) = &location {
+        offers.retain(|offer| &offer.location == location);
+    }
+
+    sort_offers!(offers, location, isp, speed_down, speed_up);
+
+    match format {
+        DumpFormat::Table => dump_table(&offers),
+        DumpFormat::Csv => {
+            let path = output.unwrap_or_else(|| PathBuf::from("offers.csv"));
+            export::write_csv(&offers, &path)?;
+            println!("Wrote {}", path.display());
+        }
+        DumpFormat::Ods => {
+            let path = output.unwrap_or_else(|| PathBuf::from("offers.ods"));
+            export::write_ods(&offers, &path)?;
+            println!("Wrote {}", path.display());
+        }
+    }
 
-    sort_offers!(offers, isp, speed_down, speed_up);
+    println!("{} offers in database", offers.len());
+    Ok(())
+}
 
-    let mut table = tabular::Table::new("{:<} {:>} {:>} {:>} {:>}");
-    table.add_row(row!("ISP", "DL/UL", "År 1", "År 2", "1+2"));
-    for offer in &offers {
+fn dump_table(offers: &[Offer]) {
+    let mut table = tabular::Table::new("{:<} {:<} {:>} {:>} {:>} {:>}");
+    table.add_row(row!("Location", "ISP", "DL/UL", "År 1", "År 2", "1+2"));
+    for offer in offers {
         let y1 = offer.calc_cost_1st_year();
         let y2 = offer.calc_cost_2nd_year();
         table.add_row(row! {
+            &offer.location,
             &offer.isp.replace(" ", "_"),
             offer.speed_str(),
             y1,
@@ -127,9 +420,6 @@ fn dump() -> Result<()> {
         });
     }
     println!("{}", table);
-
-    println!("{} offers in database", offers.len());
-    Ok(())
 }
 
 fn load_file<P: AsRef<Path>>(filename: P) -> Result<String> {
@@ -156,20 +446,108 @@ struct CmdlineOpts {
 #[derive(Subcommand)]
 enum Commands {
     /// Print the current offers in the database
-    Dump,
+    Dump {
+        /// Output format: a console table, or machine-readable CSV/ODS.
+        #[clap(long, value_enum, default_value = "table")]
+        format: DumpFormat,
+        /// Where to write the CSV/ODS file. Defaults to offers.csv/offers.ods.
+        #[clap(long)]
+        output: Option<PathBuf>,
+        /// Only show offers for this configured location.
+        #[clap(long)]
+        location: Option<String>,
+    },
     /// Ladda ner priser från Open Universe
     Update {
         /// Don't go online to download prices, use the cache only.
         #[clap(short = 'n', long = "no-download")]
         no_download: bool,
+        /// Show a desktop notification for every price drop found.
+        #[clap(long = "notify-desktop")]
+        notify_desktop: bool,
+        /// Email a summary of any price drops found. Reads SMTP settings from alerts.toml.
+        #[clap(long = "notify-email")]
+        notify_email: bool,
+        /// Minimum drop in list price or 1st-year cost to alert on, e.g. "100" (SEK) or "5%".
+        #[clap(long, default_value = "0")]
+        threshold: alert::Threshold,
+        /// How many product pages to fetch concurrently while enriching offers.
+        #[clap(long, default_value_t = DEFAULT_ENRICH_CONCURRENCY)]
+        concurrency: usize,
     },
+    /// Show how a product's price has changed over time
+    History {
+        /// Only show history for this product id, otherwise show all known products.
+        product_id: Option<u32>,
+        /// Only show history for this configured location.
+        #[clap(long)]
+        location: Option<String>,
+    },
+    /// Poll for new prices on a schedule instead of running once
+    Watch {
+        /// How long to wait between updates, e.g. "30m" or "1h".
+        interval: humantime::Duration,
+        /// Show a desktop notification for every price drop found.
+        #[clap(long = "notify-desktop")]
+        notify_desktop: bool,
+        /// Email a summary of any price drops found. Reads SMTP settings from alerts.toml.
+        #[clap(long = "notify-email")]
+        notify_email: bool,
+        /// Minimum drop in list price or 1st-year cost to alert on, e.g. "100" (SEK) or "5%".
+        #[clap(long, default_value = "0")]
+        threshold: alert::Threshold,
+        /// How many product pages to fetch concurrently while enriching offers.
+        #[clap(long, default_value_t = DEFAULT_ENRICH_CONCURRENCY)]
+        concurrency: usize,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DumpFormat {
+    Table,
+    Csv,
+    Ods,
 }
 
 fn main() -> Result<()> {
     let args: CmdlineOpts = Parser::parse();
 
     match args.command {
-        Commands::Dump => dump(),
-        Commands::Update { no_download } => update(no_download),
+        Commands::Dump {
+            format,
+            output,
+            location,
+        } => dump(format, output, location),
+        Commands::Update {
+            no_download,
+            notify_desktop,
+            notify_email,
+            threshold,
+            concurrency,
+        } => update(
+            no_download,
+            AlertOpts {
+                desktop: notify_desktop,
+                email: notify_email,
+                threshold,
+            },
+            concurrency,
+        ),
+        Commands::History { product_id, location } => history(product_id, location),
+        Commands::Watch {
+            interval,
+            notify_desktop,
+            notify_email,
+            threshold,
+            concurrency,
+        } => watch(
+            interval,
+            AlertOpts {
+                desktop: notify_desktop,
+                email: notify_email,
+                threshold,
+            },
+            concurrency,
+        ),
     }
 }